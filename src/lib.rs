@@ -1,22 +1,98 @@
-extern crate num;
 extern crate sha2;
 #[cfg(test)] extern crate rand;
 
-use num::bigint::ToBigUint;
-use num::{BigUint, Zero, One};
-use num::traits::ToPrimitive;
 use sha2::{Sha256, Digest};
 use std::fmt;
 
 pub use self::FromBase58Error::*;
 
-const BTC_ALPHA: &'static[u8] = b"123456789\
-                                  ABCDEFGHJKLMNPQRSTUVWXYZ\
-                                  abcdefghijkmnopqrstuvwxyz";
+/// The number of characters in a base58 alphabet.
+const ALPHABET_LEN: usize = 58;
 
-const FLICKR_ALPHA: &'static[u8] = b"123456789\
-                                     abcdefghijkmnopqrstuvwxyz\
-                                     ABCDEFGHJKLMNPQRSTUVWXYZ";
+const BTC_ALPHA: [u8; ALPHABET_LEN] = *b"123456789\
+                                         ABCDEFGHJKLMNPQRSTUVWXYZ\
+                                         abcdefghijkmnopqrstuvwxyz";
+
+const FLICKR_ALPHA: [u8; ALPHABET_LEN] = *b"123456789\
+                                            abcdefghijkmnopqrstuvwxyz\
+                                            ABCDEFGHJKLMNPQRSTUVWXYZ";
+
+const RIPPLE_ALPHA: [u8; ALPHABET_LEN] = *b"rpshnaf39wBUDNEGHJKLM4PQRST7VWXYZ2bcdeCg65jkm8oFqi1tuvAxyz";
+
+/// Selects which base58 alphabet `*_with` methods encode/decode against.
+///
+/// `Bitcoin` is the alphabet used by the non-`_with` methods on
+/// [`FromBase58`]/[`ToBase58`].
+#[derive(Clone, Copy)]
+pub enum Alphabet {
+    /// The alphabet used by Bitcoin addresses and most other cryptocurrencies.
+    Bitcoin,
+    /// The alphabet used by Flickr's base58-encoded short URLs.
+    Flickr,
+    /// The alphabet used by Ripple addresses.
+    Ripple,
+    /// A caller-supplied alphabet of 58 unique bytes, built with [`Alphabet::new`].
+    Custom(CustomAlphabet),
+}
+
+/// 58 bytes that have been checked for uniqueness. The field is private, so
+/// the only way to obtain one is through the validating [`Alphabet::new`]
+/// constructor — there's no way to smuggle an unchecked alphabet into
+/// `Alphabet::Custom`.
+#[derive(Clone, Copy)]
+pub struct CustomAlphabet([u8; ALPHABET_LEN]);
+
+impl Alphabet {
+    /// Builds a `Custom` alphabet from 58 bytes, failing if any byte repeats
+    /// or isn't ASCII. Bytes are required to be ASCII because `to_base58_with`
+    /// writes them straight into the result `String`.
+    pub fn new(alpha: [u8; ALPHABET_LEN]) -> Result<Alphabet, AlphabetError> {
+        for (i, &byte) in alpha.iter().enumerate() {
+            if byte >= 0x80 {
+                return Err(AlphabetError::NonAsciiByte(byte));
+            }
+            if alpha[..i].contains(&byte) {
+                return Err(AlphabetError::DuplicateByte(byte));
+            }
+        }
+        Ok(Alphabet::Custom(CustomAlphabet(alpha)))
+    }
+
+    fn as_bytes(&self) -> &[u8; ALPHABET_LEN] {
+        match *self {
+            Alphabet::Bitcoin => &BTC_ALPHA,
+            Alphabet::Flickr => &FLICKR_ALPHA,
+            Alphabet::Ripple => &RIPPLE_ALPHA,
+            Alphabet::Custom(ref custom) => &custom.0,
+        }
+    }
+}
+
+/// Errors that can occur when building a [`Alphabet::Custom`] alphabet.
+#[derive(Clone, Copy)]
+pub enum AlphabetError {
+    /// The supplied alphabet contained this byte more than once.
+    DuplicateByte(u8),
+    /// The supplied alphabet contained this byte, which isn't ASCII.
+    NonAsciiByte(u8),
+}
+
+impl fmt::Debug for AlphabetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AlphabetError::DuplicateByte(byte) =>
+                write!(f, "Alphabet byte '{}' appears more than once", byte as char),
+            AlphabetError::NonAsciiByte(byte) =>
+                write!(f, "Alphabet byte {:#x} is not ASCII", byte),
+        }
+    }
+}
+
+impl fmt::Display for AlphabetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
 
 /// A trait for converting base58-encoded values
 pub trait FromBase58 {
@@ -24,21 +100,47 @@ pub trait FromBase58 {
     /// into an owned vector of bytes, returning the vector.
     fn from_base58(&self) -> Result<Vec<u8>, FromBase58Error>;
 
+    /// Like `from_base58`, but decodes against the given `alpha` instead of
+    /// the Bitcoin alphabet.
+    fn from_base58_with(&self, alpha: &Alphabet) -> Result<Vec<u8>, FromBase58Error>;
+
     /// Converts the value of `self`, interpreted as base58check encoded data,
     /// into an owned vector of bytes, returning the vector.
     fn from_base58_check(&self) -> Result<Vec<u8>, FromBase58Error>;
+
+    /// Like `from_base58_check`, but splits off the leading
+    /// `expected_version_len` bytes of the checked payload and returns them
+    /// separately from the remaining payload, as `(version, payload)`.
+    fn from_base58_check_version(&self, expected_version_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>), FromBase58Error>;
+
+    /// Like `from_base58`, but clears and decodes into the caller-provided
+    /// `out` buffer instead of allocating a fresh one, returning the number
+    /// of bytes written. Useful when decoding many values in a hot loop. On
+    /// error, `out` is left empty.
+    fn from_base58_into(&self, out: &mut Vec<u8>) -> Result<usize, FromBase58Error>;
 }
 
 
 /// Errors that can occur when decoding a base58-encoded string or when decoding a base58check-encoded string
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum FromBase58Error {
     /// The input contained a character not part of the base58 alphabet
     InvalidBase58Byte(u8, usize),
     /// The checksum was not correct
     InvalidBase58Checksum([u8; 4], [u8; 4]),
     /// The checksum was not present
-    NoBase58Checksum
+    NoBase58Checksum,
+    /// The requested version length could not be combined with the
+    /// checksum length without overflowing
+    InvalidLength(usize),
+    /// The decoded data was shorter than the version length plus checksum
+    TooShort(usize),
+    /// The version bytes did not match what the caller expected. This crate
+    /// never constructs this variant itself; it's provided so callers
+    /// checking the version returned by `from_base58_check_version` can
+    /// report a mismatch using this error type.
+    InvalidVersion(Vec<u8>),
 }
 
 impl fmt::Debug for FromBase58Error {
@@ -49,7 +151,13 @@ impl fmt::Debug for FromBase58Error {
             InvalidBase58Checksum(chk, expected) =>
                 write!(f, "Invalid checksum '{:?}', expected {:?}", &chk, &expected),
             NoBase58Checksum =>
-                write!(f, "No checksum present")
+                write!(f, "No checksum present"),
+            InvalidLength(version_len) =>
+                write!(f, "Version length {} is too large to check against", version_len),
+            TooShort(len) =>
+                write!(f, "Decoded data of length {} is shorter than the version plus checksum", len),
+            InvalidVersion(ref version) =>
+                write!(f, "Unexpected version bytes '{:?}'", version),
         }
     }
 }
@@ -60,56 +168,66 @@ impl fmt::Display for FromBase58Error {
     }
 }
 
+impl std::error::Error for FromBase58Error {}
+
 impl FromBase58 for str {
     fn from_base58(&self) -> Result<Vec<u8>, FromBase58Error> {
         self.as_bytes().from_base58()
     }
 
+    fn from_base58_with(&self, alpha: &Alphabet) -> Result<Vec<u8>, FromBase58Error> {
+        self.as_bytes().from_base58_with(alpha)
+    }
+
     fn from_base58_check(&self) -> Result<Vec<u8>, FromBase58Error> {
         self.as_bytes().from_base58_check()
     }
+
+    fn from_base58_check_version(&self, expected_version_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>), FromBase58Error> {
+        self.as_bytes().from_base58_check_version(expected_version_len)
+    }
+
+    fn from_base58_into(&self, out: &mut Vec<u8>) -> Result<usize, FromBase58Error> {
+        self.as_bytes().from_base58_into(out)
+    }
 }
 
 impl FromBase58 for [u8] {
     // TODO: fix some of the below when the binary assignment operators +=, *=
     // are overloadable
     fn from_base58(&self) -> Result<Vec<u8>, FromBase58Error> {
-        let radix = 58.to_biguint().unwrap();
-        let mut x: BigUint = Zero::zero();
-        let mut rad_mult: BigUint = One::one();
-
-        // Convert the base58 string to a BigUint `x`
-        for (idx, &byte) in self.iter().enumerate().rev() {
-            let first_idx = BTC_ALPHA.iter()
-                                     .enumerate()
-                                     .find(|x| *x.1 == byte)
-                                     .map(|x| x.0);
-            match first_idx {
-                Some(i) => { x = x + i.to_biguint().unwrap() * &rad_mult; },
-                None => return Err(InvalidBase58Byte(self[idx], idx))
-            }
-
-            rad_mult = &rad_mult * &radix;
-        }
+        self.from_base58_with(&Alphabet::Bitcoin)
+    }
 
-        let mut r = Vec::with_capacity(self.len());
-        for _ in self.iter().take_while(|&x| *x == BTC_ALPHA[0]) {
-            r.push(0);
-        }
-        if x > Zero::zero() {
-            // TODO: use append when it becomes stable
-            r.extend(x.to_bytes_be());
-        }
-        Ok(r)
+    fn from_base58_with(&self, alpha: &Alphabet) -> Result<Vec<u8>, FromBase58Error> {
+        let mut out = Vec::new();
+        decode_into(self, alpha, &mut out)?;
+        Ok(out)
     }
 
     fn from_base58_check(&self) -> Result<Vec<u8>, FromBase58Error> {
+        self.from_base58_check_version(0).map(|(_, payload)| payload)
+    }
+
+    fn from_base58_check_version(&self, expected_version_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>), FromBase58Error> {
         let decoded = self.from_base58()?;
-        let length = decoded.len();
-        if length < 4 {
-            return Err(NoBase58Checksum)
+
+        let min_length = match expected_version_len.checked_add(4) {
+            Some(len) => len,
+            None => return Err(InvalidLength(expected_version_len)),
+        };
+        if decoded.len() < min_length {
+            return Err(if expected_version_len == 0 {
+                // Preserve `from_base58_check`'s original error for callers
+                // matching on it; `TooShort` is for the versioned case.
+                NoBase58Checksum
+            } else {
+                TooShort(decoded.len())
+            })
         }
-        let (content, check) = decoded.split_at(length-4);
+        let (content, check) = decoded.split_at(decoded.len() - 4);
 
         let first_hash = Sha256::digest(&content);
         let second_hash = Sha256::digest(&first_hash);
@@ -121,12 +239,58 @@ impl FromBase58 for [u8] {
             let mut b: [u8; 4] = Default::default();
             b.copy_from_slice(&expected_hash[..]);
             return Err(InvalidBase58Checksum(a, b))
-        } else {
-            return Ok(content.to_vec())
         }
+
+        let (version, payload) = content.split_at(expected_version_len);
+        Ok((version.to_vec(), payload.to_vec()))
+    }
+
+    fn from_base58_into(&self, out: &mut Vec<u8>) -> Result<usize, FromBase58Error> {
+        out.clear();
+        decode_into(self, &Alphabet::Bitcoin, out)?;
+        Ok(out.len())
     }
 }
 
+/// Carry-propagation radix conversion shared by `from_base58`/`from_base58_with`
+/// and `from_base58_into`: decodes `input` against `alpha`, appending the
+/// decoded bytes to `out`. `out` is used directly as the digit accumulator
+/// (assumed empty on entry), so no buffer beyond `out` itself is allocated —
+/// callers that keep reusing the same `out` (e.g. `from_base58_into`) reuse
+/// its existing capacity too. On error, `out` is left empty rather than
+/// holding the partial, un-reversed scratch data accumulated so far.
+fn decode_into(input: &[u8], alpha: &Alphabet, out: &mut Vec<u8>) -> Result<(), FromBase58Error> {
+    let alpha_bytes = alpha.as_bytes();
+    let zero_count = input.iter().take_while(|&&b| b == alpha_bytes[0]).count();
+
+    // `out` accumulates the base-256 digits of the decoded value,
+    // least-significant digit first; reversed and zero-padded below.
+    for (idx, &byte) in input.iter().enumerate().skip(zero_count) {
+        let mut carry = match alpha_bytes.iter().position(|&a| a == byte) {
+            Some(i) => i as u32,
+            None => {
+                out.clear();
+                return Err(InvalidBase58Byte(byte, idx));
+            }
+        };
+
+        let mut cursor = 0;
+        while cursor < out.len() || carry != 0 {
+            if cursor == out.len() {
+                out.push(0);
+            }
+            carry += 58 * out[cursor] as u32;
+            out[cursor] = (carry % 256) as u8;
+            carry /= 256;
+            cursor += 1;
+        }
+    }
+
+    out.reverse();
+    out.splice(0..0, std::iter::repeat(0).take(zero_count));
+    Ok(())
+}
+
 
 /// A trait for converting a value to base58 encoding.
 pub trait ToBase58 {
@@ -134,9 +298,22 @@ pub trait ToBase58 {
     /// string.
     fn to_base58(&self) -> String;
 
+    /// Like `to_base58`, but encodes against the given `alpha` instead of
+    /// the Bitcoin alphabet.
+    fn to_base58_with(&self, alpha: &Alphabet) -> String;
+
     /// Converts the value of `self` to a base-58 check value, returning the owned
     /// string.
     fn to_base58_check(&self) -> String;
+
+    /// Like `to_base58_check`, but prepends `version` to `self` before
+    /// checksumming, so the decoded payload carries a version/network byte.
+    fn to_base58_check_version(&self, version: &[u8]) -> String;
+
+    /// Like `to_base58`, but clears and encodes into the caller-provided
+    /// `out` buffer instead of allocating a fresh one, returning the number
+    /// of characters written. Useful when encoding many values in a hot loop.
+    fn to_base58_into(&self, out: &mut String) -> usize;
 }
 
 impl ToBase58 for [u8] {
@@ -148,32 +325,80 @@ impl ToBase58 for [u8] {
     // so by reading "1", no way to know if first character should be 5 or 6
     // without reading the rest
     fn to_base58(&self) -> String {
-        let radix = 58.to_biguint().unwrap();
-        let mut x = BigUint::from_bytes_be(&self);
-        let mut ans = vec![];
-        while x > Zero::zero() {
-            let rem = (&x % &radix).to_usize().unwrap();
-            ans.push(BTC_ALPHA[rem]);
-            x = &x / &radix;
-        }
+        self.to_base58_with(&Alphabet::Bitcoin)
+    }
 
-        // take care of leading zeros
-        for _ in self.iter().take_while(|&x| *x == 0) {
-            ans.push(BTC_ALPHA[0]);
-        }
-        ans.reverse();
-        String::from_utf8(ans).unwrap()
+    fn to_base58_with(&self, alpha: &Alphabet) -> String {
+        let mut out = String::new();
+        encode_into(self, alpha, &mut out);
+        out
     }
 
     fn to_base58_check(&self) -> String {
-        let first_hash = Sha256::digest(&self);
+        self.to_base58_check_version(&[])
+    }
+
+    fn to_base58_check_version(&self, version: &[u8]) -> String {
+        let mut with_version = version.to_vec();
+        with_version.extend_from_slice(self);
+
+        let first_hash = Sha256::digest(&with_version);
         let second_hash = Sha256::digest(&first_hash);
-        let mut with_check = self.iter().cloned().collect::<Vec<u8>>();
-        with_check.extend(second_hash.iter().cloned().take(4));
-        with_check.to_base58()
+        with_version.extend(second_hash.iter().cloned().take(4));
+        with_version.to_base58()
+    }
+
+    fn to_base58_into(&self, out: &mut String) -> usize {
+        out.clear();
+        encode_into(self, &Alphabet::Bitcoin, out);
+        out.len()
     }
 }
 
+/// Carry-propagation radix conversion shared by `to_base58`/`to_base58_with`
+/// and `to_base58_into`: encodes `input` against `alpha`, appending the
+/// encoded characters to `out`. `out` is assumed empty on entry and is used
+/// directly as the digit accumulator, so callers that keep reusing the same
+/// `out` (e.g. `to_base58_into`) reuse its existing capacity too, instead of
+/// allocating a separate scratch buffer on every call.
+fn encode_into(input: &[u8], alpha: &Alphabet, out: &mut String) {
+    let alpha_bytes = alpha.as_bytes();
+    let zero_count = input.iter().take_while(|&&b| b == 0).count();
+
+    // Safety: every byte this function ever writes into `buf` is either a
+    // base-58 digit value (0..58) or a byte copied from `alpha_bytes`. Digit
+    // values are always < 0x80, and `alpha_bytes` is always ASCII: the three
+    // built-in alphabets are ASCII by construction, and `Alphabet::new`
+    // rejects non-ASCII bytes before a `Custom` alphabet can be built. So
+    // `buf` holds valid single-byte UTF-8 at every point, including when we
+    // hand the String back to its owner.
+    let buf = unsafe { out.as_mut_vec() };
+
+    // `buf` accumulates the base-58 digits of the encoded value,
+    // least-significant digit first; reversed and mapped through `alpha`
+    // below, then zero-padded.
+    for &byte in &input[zero_count..] {
+        let mut carry = byte as u32;
+
+        let mut cursor = 0;
+        while cursor < buf.len() || carry != 0 {
+            if cursor == buf.len() {
+                buf.push(0);
+            }
+            carry += 256 * buf[cursor] as u32;
+            buf[cursor] = (carry % 58) as u8;
+            carry /= 58;
+            cursor += 1;
+        }
+    }
+
+    buf.reverse();
+    for digit in buf.iter_mut() {
+        *digit = alpha_bytes[*digit as usize];
+    }
+    buf.splice(0..0, std::iter::repeat(alpha_bytes[0]).take(zero_count));
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -304,4 +529,126 @@ mod tests {
                        v);
         }
     }
+
+    #[test]
+    fn test_to_base58_with_flickr_and_ripple() {
+        assert_eq!(b"abc".to_base58_with(&super::Alphabet::Flickr), "yHcz");
+        assert_eq!(b"abc".to_base58_with(&super::Alphabet::Ripple), "Z5U2");
+        assert_eq!(b"\0\0abc".to_base58_with(&super::Alphabet::Flickr), "11yHcz");
+    }
+
+    #[test]
+    fn test_from_base58_with_flickr_and_ripple() {
+        assert_eq!("yHcz".from_base58_with(&super::Alphabet::Flickr).unwrap(), b"abc");
+        assert_eq!("Z5U2".from_base58_with(&super::Alphabet::Ripple).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_alphabet_custom_rejects_duplicate_byte() {
+        let dup = [b'1'; 58];
+        match super::Alphabet::new(dup) {
+            Err(super::AlphabetError::DuplicateByte(b'1')) => (),
+            _ => panic!("expected DuplicateByte('1')"),
+        }
+    }
+
+    #[test]
+    fn test_alphabet_custom_rejects_non_ascii_byte() {
+        let mut non_ascii = [0u8; 58];
+        for (i, byte) in non_ascii.iter_mut().enumerate() {
+            *byte = 198 + i as u8;
+        }
+        match super::Alphabet::new(non_ascii) {
+            Err(super::AlphabetError::NonAsciiByte(198)) => (),
+            _ => panic!("expected NonAsciiByte(198)"),
+        }
+    }
+
+    #[test]
+    fn test_alphabet_custom_roundtrip() {
+        let alpha = super::Alphabet::new(*b"123456789\
+                                            ABCDEFGHJKLMNPQRSTUVWXYZ\
+                                            abcdefghijkmnopqrstuvwxyz").unwrap();
+        assert_eq!(b"abc".to_base58_with(&alpha), "ZiCa");
+        assert_eq!("ZiCa".from_base58_with(&alpha).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_to_base58_carry_propagation_multibyte() {
+        // Exercises the carry-propagation radix conversion over more bytes
+        // than fit in a single carry (every byte is the max carry value).
+        let data = [0xFFu8; 16];
+        assert_eq!(data.to_base58(), "YcVfxkQb6JRzqk5kF2tNLv");
+        assert_eq!("YcVfxkQb6JRzqk5kF2tNLv".from_base58().unwrap(), &data[..]);
+    }
+
+    #[test]
+    fn test_to_base58_check_version_basic() {
+        assert_eq!(b"1234598760".to_base58_check_version(&[0]), "1K5zqBMZZTzUbAaWeMzf");
+    }
+
+    #[test]
+    fn test_from_base58_check_version_basic() {
+        let (version, payload) = "1K5zqBMZZTzUbAaWeMzf".from_base58_check_version(1).unwrap();
+        assert_eq!(version, &[0]);
+        assert_eq!(payload, b"1234598760");
+    }
+
+    #[test]
+    fn test_from_base58_check_version_too_short() {
+        match "3QJ".from_base58_check_version(0) {
+            Err(super::NoBase58Checksum) => (),
+            _ => panic!("expected NoBase58Checksum"),
+        }
+        match "3QJ".from_base58_check_version(1) {
+            Err(super::TooShort(_)) => (),
+            _ => panic!("expected TooShort"),
+        }
+    }
+
+    #[test]
+    fn test_from_base58_check_version_invalid_length_on_overflow() {
+        match "".from_base58_check_version(usize::max_value()) {
+            Err(super::InvalidLength(_)) => (),
+            _ => panic!("expected InvalidLength"),
+        }
+    }
+
+    #[test]
+    fn test_from_base58_error_is_std_error() {
+        fn assert_error<E: std::error::Error>(_: &E) {}
+        let err = "0".from_base58().unwrap_err();
+        assert_error(&err);
+    }
+
+    #[test]
+    fn test_from_base58_into_reuses_out() {
+        let mut out = Vec::new();
+        assert_eq!("ZiCa".from_base58_into(&mut out).unwrap(), 3);
+        assert_eq!(out, b"abc");
+
+        // A second, shorter decode must clear the previous contents rather
+        // than appending to them.
+        assert_eq!("Z".from_base58_into(&mut out).unwrap(), 1);
+        assert_eq!(out, &[32]);
+    }
+
+    #[test]
+    fn test_from_base58_into_clears_out_on_error() {
+        let mut out = vec![9, 9, 9];
+        assert!("Z0".from_base58_into(&mut out).is_err());
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_to_base58_into_reuses_out() {
+        let mut out = String::new();
+        assert_eq!(b"abc".to_base58_into(&mut out), 4);
+        assert_eq!(out, "ZiCa");
+
+        // A second, shorter encode must clear the previous contents rather
+        // than appending to them.
+        assert_eq!([32].to_base58_into(&mut out), 1);
+        assert_eq!(out, "Z");
+    }
 }